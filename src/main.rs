@@ -1,10 +1,12 @@
 use anyhow::Context;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use axum::{
-    Json, Router,
-    extract::{Path, Query, State},
-    http::StatusCode,
+    Extension, Json, Router,
+    extract::{Path, Query, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post, put},
 };
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
@@ -12,6 +14,8 @@ use serde_json::json;
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use std::env;
 
+mod reports;
+
 // --- СТРУКТУРЫ ДАННЫХ ---
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +24,204 @@ pub struct PeriodParams {
     pub end: NaiveDate,
 }
 
+// --- ПОИСК ---
+
+// Параметры полнотекстового поиска по каталогу материалов.
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    pub limit: Option<i64>,
+    pub group_code: Option<String>,
+}
+
+// Частичное обновление строки каталога материалов (название/класс).
+#[derive(Debug, Deserialize)]
+pub struct UpdateMaterialRequest {
+    pub material_name: Option<String>,
+    pub class_code: Option<String>,
+}
+
+// --- ЭКСПОРТ (CSV/JSON) ---
+
+// Распознается по `?format=csv` в query-строке или заголовку `Accept: text/csv`.
+// JSON остается форматом по умолчанию.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl<S> axum::extract::FromRequestParts<S> for ExportFormat
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let wants_csv_query = parts
+            .uri
+            .query()
+            .map(|q| q.split('&').any(|pair| pair == "format=csv"))
+            .unwrap_or(false);
+
+        let wants_csv_header = parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains("text/csv"))
+            .unwrap_or(false);
+
+        Ok(if wants_csv_query || wants_csv_header {
+            ExportFormat::Csv
+        } else {
+            ExportFormat::Json
+        })
+    }
+}
+
+fn rows_to_csv<T: Serialize>(rows: &[T]) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+// Общая обертка для списочных аналитических ручек: в JSON отдает данные как обычно,
+// в CSV — сериализует строки через csv::Writer с заголовком и `Content-Disposition`.
+// Так формат экспорта не нужно переписывать в каждом хендлере отдельно.
+pub struct Export<T: Serialize> {
+    pub data: Vec<T>,
+    pub filename: &'static str,
+}
+
+impl<T: Serialize> Export<T> {
+    fn into_response_for(self, format: ExportFormat) -> Response {
+        match format {
+            ExportFormat::Json => Json(self.data).into_response(),
+            ExportFormat::Csv => match rows_to_csv(&self.data) {
+                Ok(body) => (
+                    [
+                        (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                        (
+                            header::CONTENT_DISPOSITION,
+                            format!("attachment; filename=\"{}.csv\"", self.filename),
+                        ),
+                    ],
+                    body,
+                )
+                    .into_response(),
+                Err(err) => AppError(err).into_response(),
+            },
+        }
+    }
+}
+
+// --- ПАГИНАЦИЯ ---
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+// Общие параметры постраничного вывода для всех списочных ручек.
+pub trait Pageable {
+    fn limit(&self) -> i64;
+    fn page(&self) -> i64;
+}
+
+// Постраничный вывод без фильтра по дате — для списков без колонки с датой
+// (Suppliers, Material_Catalog), где since/until были бы молчаливым no-op.
+#[derive(Debug, Deserialize)]
+pub struct PageOptions {
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+impl PageOptions {
+    // Размер страницы с дефолтом и верхней границей, чтобы не уронить базу одним запросом.
+    pub fn limit(&self) -> i64 {
+        self.page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        let page = self.page.unwrap_or(1).max(1);
+        (page - 1) * self.limit()
+    }
+}
+
+impl Pageable for PageOptions {
+    fn limit(&self) -> i64 {
+        PageOptions::limit(self)
+    }
+
+    fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+}
+
+// Постраничный вывод + фильтр по дате — для списков, построенных поверх Storage_Units,
+// у которых есть колонка date, по которой since/until реально фильтруют.
+// Поля постраничного вывода не вынесены через serde(flatten) в PageOptions — Query
+// использует serde_urlencoded, где flatten не всегда ведет себя предсказуемо.
+#[derive(Debug, Deserialize)]
+pub struct ListOptions {
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl ListOptions {
+    pub fn limit(&self) -> i64 {
+        self.page_size
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        let page = self.page.unwrap_or(1).max(1);
+        (page - 1) * self.limit()
+    }
+}
+
+impl Pageable for ListOptions {
+    fn limit(&self) -> i64 {
+        ListOptions::limit(self)
+    }
+
+    fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+}
+
+// Обертка над ответом для списочных ручек: данные + курсор на следующую страницу + total.
+#[derive(Debug, Serialize)]
+pub struct Paginated<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total: Option<i64>,
+}
+
+impl<T> Paginated<T> {
+    // Строит ответ и сам решает, есть ли следующая страница (по тому, заполнена ли текущая).
+    fn new(data: Vec<T>, opts: &impl Pageable, total: Option<i64>) -> Self {
+        let next_cursor = if data.len() as i64 >= opts.limit() {
+            Some((opts.page() + 1).to_string())
+        } else {
+            None
+        };
+        Self {
+            data,
+            next_cursor,
+            total,
+        }
+    }
+}
+
 // 1. Название поставщика по ИНН
 #[derive(Debug, Serialize)]
 pub struct SupplierName {
@@ -61,6 +263,28 @@ pub struct InventoryValue {
     pub total_value: Option<f64>,
 }
 
+// Запрос на запись наблюдения цены материала (для истории цен).
+#[derive(Debug, Deserialize)]
+pub struct PriceObservationRequest {
+    pub supplier_id: i32,
+    pub unit_price: f64,
+}
+
+// Одна точка временного ряда истории цены материала.
+#[derive(Debug, Serialize)]
+pub struct PricePoint {
+    pub observed_at: chrono::NaiveDateTime,
+    pub unit_price: f64,
+}
+
+// Сводка min/max/avg по истории цены материала за период.
+#[derive(Debug, Serialize)]
+pub struct PriceRollup {
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    pub avg_price: Option<f64>,
+}
+
 // 8. Доля поставщика в поставке товаров одной группы
 #[derive(Debug, Serialize)]
 pub struct SupplierShare {
@@ -81,8 +305,59 @@ pub struct OrderBankInfo {
     pub total_amount: Option<f64>,
 }
 
+// 6. Запрос на списание материала со склада
+#[derive(Debug, Deserialize)]
+pub struct WithdrawRequest {
+    pub material_id: i32,
+    pub quantity: f64,
+    pub order_number: i32,
+}
+
+// 6. Запись в журнале списаний
+#[derive(Debug, Serialize)]
+pub struct Withdrawal {
+    pub withdrawal_id: i32,
+    pub material_id: i32,
+    pub quantity: f64,
+    pub order_number: i32,
+    pub withdrawn_at: chrono::NaiveDateTime,
+}
+
 // --- ОБРАБОТКА ОШИБОК (ANYHOW + JSON) ---
 
+// Маркер для конфликтов бизнес-логики (например, недостаточно остатка на складе).
+// Заворачивается в anyhow и распознается через downcast_ref в IntoResponse, как и sqlx::Error.
+#[derive(Debug)]
+pub struct InsufficientStock {
+    pub material_id: i32,
+    pub requested: f64,
+    pub available: f64,
+}
+
+impl std::fmt::Display for InsufficientStock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "недостаточно остатка материала {}: запрошено {}, доступно {}",
+            self.material_id, self.requested, self.available
+        )
+    }
+}
+
+impl std::error::Error for InsufficientStock {}
+
+// Маркер для ошибок аутентификации/авторизации, распознается в IntoResponse как 401.
+#[derive(Debug)]
+pub struct Unauthorized(pub String);
+
+impl std::fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Unauthorized {}
+
 // Обертка для ошибок, чтобы реализовать IntoResponse
 pub struct AppError(anyhow::Error);
 
@@ -99,18 +374,26 @@ where
 // Логика превращения ошибки в HTTP ответ (JSON)
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self.0.downcast_ref::<sqlx::Error>() {
-            Some(sqlx::Error::RowNotFound) => {
-                (StatusCode::NOT_FOUND, "Resource not found".to_string())
+        let (status, error_message) = if let Some(stock_err) =
+            self.0.downcast_ref::<InsufficientStock>()
+        {
+            (StatusCode::CONFLICT, stock_err.to_string())
+        } else if let Some(auth_err) = self.0.downcast_ref::<Unauthorized>() {
+            (StatusCode::UNAUTHORIZED, auth_err.to_string())
+        } else {
+            match self.0.downcast_ref::<sqlx::Error>() {
+                Some(sqlx::Error::RowNotFound) => {
+                    (StatusCode::NOT_FOUND, "Resource not found".to_string())
+                }
+                Some(_) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error".to_string(),
+                ),
+                None => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    self.0.to_string(), // Возвращаем текст ошибки anyhow (будь осторожен с чувствительными данными в проде)
+                ),
             }
-            Some(_) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Database error".to_string(),
-            ),
-            None => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                self.0.to_string(), // Возвращаем текст ошибки anyhow (будь осторожен с чувствительными данными в проде)
-            ),
         };
 
         let body = Json(json!({
@@ -125,6 +408,94 @@ impl IntoResponse for AppError {
 // Тип ответа хендлеров: Успех (JSON) или Ошибка (AppError -> JSON)
 type HandlerResult<T> = Result<Json<T>, AppError>;
 
+// --- АУТЕНТИФИКАЦИЯ ---
+
+// Кладется в request extensions после успешной проверки токена,
+// чтобы хендлеры могли узнать, кто делает запрос и что ему разрешено.
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub account_id: i32,
+    pub scope: String,
+}
+
+fn verify_token(hash: &str, token: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(token.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+// Middleware: достает `Authorization: Bearer <token>`, сверяет с argon2-хешами из
+// api_tokens и, если все ок, кладет AuthContext в extensions запроса.
+pub async fn require_token(
+    State(pool): State<PgPool>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| Unauthorized("отсутствует Bearer-токен".to_string()))?
+        .to_string();
+
+    // Токен имеет вид "<token_id>.<secret>": token_id — это публичный нечувствительный
+    // идентификатор для поиска строки в api_tokens, а argon2 проверяется только против
+    // одной найденной записи, а не перебором всех хешей в таблице.
+    let (token_id, secret) = token
+        .split_once('.')
+        .ok_or_else(|| Unauthorized("неверный формат токена".to_string()))?;
+
+    let row = sqlx::query!(
+        "SELECT account_id, token_hash, scope FROM api_tokens WHERE token_id = $1",
+        token_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let auth = match row {
+        Some(row) if verify_token(&row.token_hash, secret) => AuthContext {
+            account_id: row.account_id,
+            scope: row.scope,
+        },
+        _ => return Err(Unauthorized("неверный токен".to_string()).into()),
+    };
+
+    // Лучше-чем-ничего: это аудит-лог, навешанный на require_token глобально для всего API,
+    // так что сбой записи в него не должен превращать валидный токен в 500 для всего приложения
+    // (ср. send_digest_email в reports.rs — тоже best-effort, ошибки только логируются).
+    let path = req.uri().path().to_string();
+    if let Err(err) = sqlx::query!(
+        "INSERT INTO token_access_log (account_id, path, accessed_at) VALUES ($1, $2, now())",
+        auth.account_id,
+        path
+    )
+    .execute(&pool)
+    .await
+    {
+        eprintln!("Не удалось записать token_access_log: {err:#}");
+    }
+
+    req.extensions_mut().insert(auth);
+    Ok(next.run(req).await)
+}
+
+// Middleware: допускает к ручкам записи только токены со scope "write".
+// Навешивается поверх require_token на каждый маршрут записи по отдельности.
+pub async fn require_write_scope(
+    Extension(auth): Extension<AuthContext>,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if auth.scope != "write" {
+        return Err(Unauthorized("токен не дает прав на запись".to_string()).into());
+    }
+    Ok(next.run(req).await)
+}
+
 // --- ХЕНДЛЕРЫ ---
 
 // 1. GET /api/suppliers/by-tax/:tax_id
@@ -150,66 +521,214 @@ pub async fn get_supplier_name_by_tax(
 pub async fn get_suppliers_by_bank_city(
     State(pool): State<PgPool>,
     Path(city): Path<String>,
-) -> HandlerResult<Vec<SupplierBankInfo>> {
+    Query(opts): Query<PageOptions>,
+) -> HandlerResult<Paginated<SupplierBankInfo>> {
     let suppliers = sqlx::query_as!(
         SupplierBankInfo,
-        "SELECT name, tax_id FROM Suppliers WHERE bank_address_city = $1",
-        city
+        "SELECT name, tax_id FROM Suppliers WHERE bank_address_city = $1 ORDER BY name LIMIT $2 OFFSET $3",
+        city,
+        opts.limit(),
+        opts.offset()
     )
     .fetch_all(&pool)
     .await?;
 
-    Ok(Json(suppliers))
+    let total = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM Suppliers WHERE bank_address_city = $1",
+        city
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(Paginated::new(suppliers, &opts, total)))
 }
 
 // 3. GET /api/analytics/bank-supplier-count
 pub async fn get_suppliers_per_bank(
     State(pool): State<PgPool>,
-) -> HandlerResult<Vec<BankSupplierCount>> {
+    Query(opts): Query<PageOptions>,
+    format: ExportFormat,
+) -> Result<Response, AppError> {
+    // CSV идет аналитикам целиком, без постраничной обрезки — LIMIT/OFFSET NULL
+    // в Postgres означает "без ограничения".
+    let (limit, offset) = match format {
+        ExportFormat::Csv => (None, None),
+        ExportFormat::Json => (Some(opts.limit()), Some(opts.offset())),
+    };
+
     let counts = sqlx::query_as!(
         BankSupplierCount,
         r#"
-        SELECT 
-            bank_address_city, 
+        SELECT
+            bank_address_city,
             COUNT(supplier_id) AS "supplier_count"
-        FROM Suppliers 
-        WHERE bank_address_city IS NOT NULL 
+        FROM Suppliers
+        WHERE bank_address_city IS NOT NULL
         GROUP BY bank_address_city
         ORDER BY "supplier_count" DESC
-        "#
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset
     )
     .fetch_all(&pool)
     .await?;
 
-    Ok(Json(counts))
+    let total = sqlx::query_scalar!(
+        "SELECT COUNT(DISTINCT bank_address_city) FROM Suppliers WHERE bank_address_city IS NOT NULL"
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(match format {
+        ExportFormat::Csv => Export {
+            data: counts,
+            filename: "suppliers-per-bank",
+        }
+        .into_response_for(format),
+        ExportFormat::Json => Json(Paginated::new(counts, &opts, total)).into_response(),
+    })
 }
 
 // 4. GET /api/materials/by-group/:group_code
 pub async fn get_materials_by_group(
     State(pool): State<PgPool>,
     Path(group_code): Path<String>,
-) -> HandlerResult<Vec<MaterialAssortment>> {
+    Query(opts): Query<PageOptions>,
+) -> HandlerResult<Paginated<MaterialAssortment>> {
     let assortment = sqlx::query_as!(
         MaterialAssortment,
-        "SELECT material_name, class_code FROM Material_Catalog WHERE group_code = $1",
+        "SELECT material_name, class_code FROM Material_Catalog WHERE group_code = $1 ORDER BY material_name LIMIT $2 OFFSET $3",
+        group_code,
+        opts.limit(),
+        opts.offset()
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM Material_Catalog WHERE group_code = $1",
         group_code
     )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(Paginated::new(assortment, &opts, total)))
+}
+
+// Пересчитывает поисковый индекс (tsvector) для одного материала.
+// Вызывается из update_material после изменения строки Material_Catalog,
+// чтобы поиск не расходился с каталогом (держим колонку search_vector в актуальном состоянии).
+pub async fn sync_material_search_index(pool: &PgPool, material_id: i32) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE Material_Catalog
+        SET search_vector = to_tsvector('russian', material_name)
+        WHERE material_id = $1
+        "#,
+        material_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Первоначальное наполнение search_vector для строк каталога, у которых он еще не заполнен
+// (всех, что существовали до появления поиска — sync_material_search_index их никогда не
+// трогал, так как он вызывается только из update_material по одной строке за раз). Трогает
+// только NULL-строки, поэтому запускать ее при каждом старте сервера безопасно и дешево.
+pub async fn backfill_search_index(pool: &PgPool) -> anyhow::Result<u64> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE Material_Catalog
+        SET search_vector = to_tsvector('russian', material_name)
+        WHERE search_vector IS NULL
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// PUT /api/materials/:material_id
+// Единственный путь записи в каталог материалов — пересчитывает search_vector сразу
+// после обновления строки, чтобы полнотекстовый индекс не расходился с каталогом.
+pub async fn update_material(
+    State(pool): State<PgPool>,
+    Path(material_id): Path<i32>,
+    Json(req): Json<UpdateMaterialRequest>,
+) -> HandlerResult<MaterialAssortment> {
+    let updated = sqlx::query_as!(
+        MaterialAssortment,
+        r#"
+        UPDATE Material_Catalog
+        SET
+            material_name = COALESCE($2, material_name),
+            class_code = COALESCE($3, class_code)
+        WHERE material_id = $1
+        RETURNING material_name, class_code
+        "#,
+        material_id,
+        req.material_name,
+        req.class_code
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    sync_material_search_index(&pool, material_id).await?;
+
+    Ok(Json(updated))
+}
+
+// GET /api/search/materials?q=...&limit=...&group_code=...
+// Нечеткий/префиксный поиск: полнотекстовый поиск по tsvector плюс pg_trgm для опечаток
+// (точный group_code из get_materials_by_group тут не годится — пользователю нужна
+// терпимость к ошибкам в названии).
+pub async fn search_materials(
+    State(pool): State<PgPool>,
+    Query(params): Query<SearchParams>,
+) -> HandlerResult<Vec<MaterialAssortment>> {
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+
+    let hits = sqlx::query_as!(
+        MaterialAssortment,
+        r#"
+        SELECT material_name, class_code
+        FROM Material_Catalog
+        WHERE ($3::text IS NULL OR group_code = $3)
+          AND (
+            search_vector @@ plainto_tsquery('russian', $1)
+            OR material_name % $1
+            OR material_name ILIKE ($1 || '%')
+          )
+        ORDER BY
+            ts_rank(search_vector, plainto_tsquery('russian', $1)) DESC,
+            similarity(material_name, $1) DESC
+        LIMIT $2
+        "#,
+        params.q,
+        limit,
+        params.group_code
+    )
     .fetch_all(&pool)
     .await?;
 
-    Ok(Json(assortment))
+    Ok(Json(hits))
 }
 
 // 5. GET /api/finance/total-spent?start=...&end=...
 pub async fn get_total_spent_by_period(
     State(pool): State<PgPool>,
     Query(params): Query<PeriodParams>,
-) -> HandlerResult<TotalAmount> {
+    format: ExportFormat,
+) -> Result<Response, AppError> {
     let result = sqlx::query_as!(
         TotalAmount,
         r#"
-        SELECT SUM(quantity * unit_price)::float AS total_amount 
-        FROM Storage_Units 
+        SELECT SUM(quantity * unit_price)::float AS total_amount
+        FROM Storage_Units
         WHERE date BETWEEN $1 AND $2
         "#,
         params.start,
@@ -218,45 +737,258 @@ pub async fn get_total_spent_by_period(
     .fetch_one(&pool)
     .await?;
 
-    Ok(Json(result))
+    Ok(match format {
+        ExportFormat::Csv => Export {
+            data: vec![result],
+            filename: "total-spent",
+        }
+        .into_response_for(format),
+        ExportFormat::Json => Json(result).into_response(),
+    })
 }
 
-// 6. GET /api/inventory/withdrawn (Заглушка)
+// 6. GET /api/inventory/withdrawn?start=...&end=...
 pub async fn get_withdrawn_materials(
-    _state: State<PgPool>,
-    _query: Query<PeriodParams>,
-) -> HandlerResult<Vec<String>> {
-    // Явно создаем ошибку, которая превратится в JSON
-    // Т.к. это NotImplemented, можно было бы сделать кастомный статус,
-    // но для примера вернем 500 через anyhow или кастомную логику.
+    State(pool): State<PgPool>,
+    Query(params): Query<PeriodParams>,
+) -> HandlerResult<Vec<Withdrawal>> {
+    let log = sqlx::query_as!(
+        Withdrawal,
+        r#"
+        SELECT withdrawal_id, material_id, quantity::float AS "quantity!", order_number, withdrawn_at
+        FROM Withdrawals
+        WHERE withdrawn_at::date BETWEEN $1 AND $2
+        ORDER BY withdrawn_at DESC
+        "#,
+        params.start,
+        params.end
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(log))
+}
+
+// 6. POST /api/inventory/withdraw
+// Списывает материал со склада транзакционно: проверяет остаток, пишет журнал,
+// уменьшает количество по самым старым партиям (FIFO), коммитит только если хватило остатка.
+pub async fn withdraw_material(
+    State(pool): State<PgPool>,
+    Json(req): Json<WithdrawRequest>,
+) -> HandlerResult<Withdrawal> {
+    if req.quantity <= 0.0 {
+        return Err(anyhow::anyhow!("quantity должно быть положительным").into());
+    }
+
+    let mut tx = pool.begin().await?;
+
+    // Блокируем партии материала сразу, чтобы сумма остатка и списание считались
+    // по одному и тому же согласованному снимку строк — иначе два одновременных
+    // списания могут пройти проверку против одного и того же устаревшего total.
+    let batches = sqlx::query!(
+        r#"
+        SELECT storage_unit_id, quantity
+        FROM Storage_Units
+        WHERE material_id = $1 AND quantity > 0
+        ORDER BY date ASC
+        FOR UPDATE
+        "#,
+        req.material_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let available: f64 = batches.iter().map(|batch| batch.quantity).sum();
+
+    if available < req.quantity {
+        return Err(InsufficientStock {
+            material_id: req.material_id,
+            requested: req.quantity,
+            available,
+        }
+        .into());
+    }
+
+    // Списываем с самых старых партий.
+    let mut remaining = req.quantity;
+    for batch in batches {
+        if remaining <= 0.0 {
+            break;
+        }
+        let take = remaining.min(batch.quantity);
+        sqlx::query!(
+            "UPDATE Storage_Units SET quantity = quantity - $1 WHERE storage_unit_id = $2",
+            take,
+            batch.storage_unit_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        remaining -= take;
+    }
+
+    if remaining > 0.0 {
+        return Err(InsufficientStock {
+            material_id: req.material_id,
+            requested: req.quantity,
+            available,
+        }
+        .into());
+    }
 
-    // Для более красивого кода лучше использовать (StatusCode, Json) напрямую,
-    // но требование было "через anyhow" или единообразно.
-    // Сделаем так:
-    Err(anyhow::anyhow!("Метод не реализован: отсутствует таблица расхода").into())
+    let withdrawal = sqlx::query_as!(
+        Withdrawal,
+        r#"
+        INSERT INTO Withdrawals (material_id, quantity, order_number, withdrawn_at)
+        VALUES ($1, $2, $3, now())
+        RETURNING withdrawal_id, material_id, quantity::float AS "quantity!", order_number, withdrawn_at
+        "#,
+        req.material_id,
+        req.quantity,
+        req.order_number
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(withdrawal))
 }
 
 // 7. GET /api/inventory/stock-value
 pub async fn get_current_inventory_value(
     State(pool): State<PgPool>,
-) -> HandlerResult<Vec<InventoryValue>> {
+    Query(opts): Query<ListOptions>,
+    format: ExportFormat,
+) -> Result<Response, AppError> {
+    // CSV идет аналитикам целиком, без постраничной обрезки — LIMIT/OFFSET NULL
+    // в Postgres означает "без ограничения".
+    let (limit, offset) = match format {
+        ExportFormat::Csv => (None, None),
+        ExportFormat::Json => (Some(opts.limit()), Some(opts.offset())),
+    };
+
     let inventory = sqlx::query_as!(
         InventoryValue,
         r#"
-        SELECT 
+        SELECT
             MC.material_name,
             SUM(SU.quantity)::float AS total_quantity,
             SUM(SU.quantity * SU.unit_price)::float AS total_value
         FROM Storage_Units SU
         JOIN Material_Catalog MC ON SU.material_id = MC.material_id
+        WHERE ($1::date IS NULL OR SU.date >= $1)
+          AND ($2::date IS NULL OR SU.date <= $2)
         GROUP BY MC.material_name
         ORDER BY total_value DESC
+        LIMIT $3 OFFSET $4
         "#,
+        opts.since,
+        opts.until,
+        limit,
+        offset
     )
     .fetch_all(&pool)
     .await?;
 
-    Ok(Json(inventory))
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT MC.material_name)
+        FROM Storage_Units SU
+        JOIN Material_Catalog MC ON SU.material_id = MC.material_id
+        WHERE ($1::date IS NULL OR SU.date >= $1)
+          AND ($2::date IS NULL OR SU.date <= $2)
+        "#,
+        opts.since,
+        opts.until
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(match format {
+        ExportFormat::Csv => Export {
+            data: inventory,
+            filename: "inventory-value",
+        }
+        .into_response_for(format),
+        ExportFormat::Json => Json(Paginated::new(inventory, &opts, total)).into_response(),
+    })
+}
+
+// POST /api/materials/:material_id/price-observations
+// Записывает цену поставщика на материал. Неизменившиеся цены схлопываются в одну
+// строку (first_seen/last_seen) вместо строки на каждый опрос.
+pub async fn record_price_observation(
+    State(pool): State<PgPool>,
+    Path(material_id): Path<i32>,
+    Json(req): Json<PriceObservationRequest>,
+) -> HandlerResult<PricePoint> {
+    let observation = sqlx::query_as!(
+        PricePoint,
+        r#"
+        INSERT INTO price_observations (material_id, supplier_id, unit_price, first_seen, last_seen)
+        VALUES ($1, $2, $3, now(), now())
+        ON CONFLICT (material_id, supplier_id, unit_price)
+        DO UPDATE SET last_seen = now()
+        RETURNING last_seen AS "observed_at!", unit_price
+        "#,
+        material_id,
+        req.supplier_id,
+        req.unit_price
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(observation))
+}
+
+// GET /api/materials/:material_id/price-history?start=...&end=...
+pub async fn get_price_history(
+    State(pool): State<PgPool>,
+    Path(material_id): Path<i32>,
+    Query(params): Query<PeriodParams>,
+) -> HandlerResult<Vec<PricePoint>> {
+    let history = sqlx::query_as!(
+        PricePoint,
+        r#"
+        SELECT first_seen AS "observed_at!", unit_price
+        FROM price_observations
+        WHERE material_id = $1 AND last_seen::date >= $2 AND first_seen::date <= $3
+        ORDER BY first_seen ASC
+        "#,
+        material_id,
+        params.start,
+        params.end
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(history))
+}
+
+// GET /api/materials/:material_id/price-rollup?start=...&end=...
+pub async fn get_price_rollup(
+    State(pool): State<PgPool>,
+    Path(material_id): Path<i32>,
+    Query(params): Query<PeriodParams>,
+) -> HandlerResult<PriceRollup> {
+    let rollup = sqlx::query_as!(
+        PriceRollup,
+        r#"
+        SELECT
+            MIN(unit_price) AS min_price,
+            MAX(unit_price) AS max_price,
+            AVG(unit_price) AS avg_price
+        FROM price_observations
+        WHERE material_id = $1 AND last_seen::date >= $2 AND first_seen::date <= $3
+        "#,
+        material_id,
+        params.start,
+        params.end
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(rollup))
 }
 
 // 8. GET /api/analytics/supplier-share/:supplier_id/:group_code
@@ -291,28 +1023,39 @@ pub async fn get_supplier_share(
 }
 
 // 9. GET /api/inventory/monthly-load/:year
-// pub async fn get_monthly_load(
-//    State(pool): State<PgPool>,
-//    Path(year): Path<i32>,
-//) -> HandlerResult<Vec<MonthlyLoad>> {
-//    let load = sqlx::query_as!(
-//        MonthlyLoad,
-//        r#"
-//        SELECT
-//            EXTRACT(MONTH FROM date) AS month,
-//            SUM(quantity * unit_price)::float AS monthly_value
-//        FROM Storage_Units
-//        WHERE EXTRACT(YEAR FROM date) = $1
-//       GROUP BY month
-//        ORDER BY month
-//        "#,
-//        year
-//    )
-//    .fetch_all(&pool)
-//    .await?;
-//
-//    Ok(Json(load))
-//}
+pub async fn get_monthly_load(
+    State(pool): State<PgPool>,
+    Path(year): Path<i32>,
+) -> HandlerResult<Vec<MonthlyLoad>> {
+    let load = sqlx::query_as!(
+        MonthlyLoad,
+        r#"
+        SELECT
+            EXTRACT(MONTH FROM date) AS month,
+            SUM(quantity * unit_price)::float AS monthly_value
+        FROM Storage_Units
+        WHERE EXTRACT(YEAR FROM date) = $1
+        GROUP BY month
+        ORDER BY month
+        "#,
+        year
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(load))
+}
+
+// GET /api/reports/weekly
+// Отдает тот же дайджест, что и фоновая рассылка, но по запросу и в JSON.
+pub async fn get_weekly_report(State(pool): State<PgPool>) -> HandlerResult<reports::Digest> {
+    let period_end = chrono::Utc::now().date_naive();
+    let period_start = period_end - chrono::Duration::days(7);
+
+    let digest = reports::build_digest(&pool, period_start, period_end).await?;
+
+    Ok(Json(digest))
+}
 
 // 10. GET /api/orders/:order_number/bank-info
 pub async fn get_bank_info_by_order(
@@ -357,6 +1100,13 @@ async fn main() -> anyhow::Result<()> {
 
     println!("Успешное подключение к БД");
 
+    let backfilled = backfill_search_index(&pool)
+        .await
+        .context("не удалось выполнить начальное наполнение search_vector")?;
+    if backfilled > 0 {
+        println!("search_vector заполнен для {backfilled} ранее не проиндексированных строк каталога");
+    }
+
     let app = Router::new()
         // 1. ИСПРАВЛЕНО: {tax_id}
         .route(
@@ -378,28 +1128,59 @@ async fn main() -> anyhow::Result<()> {
             "/api/materials/by-group/{group_code}",
             get(get_materials_by_group),
         )
+        // Поиск по каталогу материалов
+        .route("/api/search/materials", get(search_materials))
+        .route(
+            "/api/materials/{material_id}",
+            put(update_material).layer(middleware::from_fn(require_write_scope)),
+        )
         // 5
         .route("/api/finance/total-spent", get(get_total_spent_by_period))
-        // 6 (Заглушка)
+        // 6
         .route("/api/inventory/withdrawn", get(get_withdrawn_materials))
+        .route(
+            "/api/inventory/withdraw",
+            post(withdraw_material).layer(middleware::from_fn(require_write_scope)),
+        )
         // 7
         .route(
             "/api/inventory/stock-value",
             get(get_current_inventory_value),
         )
+        // История цены материала
+        .route(
+            "/api/materials/{material_id}/price-observations",
+            post(record_price_observation).layer(middleware::from_fn(require_write_scope)),
+        )
+        .route(
+            "/api/materials/{material_id}/price-history",
+            get(get_price_history),
+        )
+        .route(
+            "/api/materials/{material_id}/price-rollup",
+            get(get_price_rollup),
+        )
         // 8. ИСПРАВЛЕНО: {supplier_id} и {group_code}
         .route(
             "/api/analytics/supplier-share/{supplier_id}/{group_code}",
             get(get_supplier_share),
         )
         // 9. ИСПРАВЛЕНО: {year}
-        //        .route("/api/inventory/monthly-load/{year}", get(get_monthly_load))
+        .route(
+            "/api/inventory/monthly-load/{year}",
+            get(get_monthly_load),
+        )
         // 10. ИСПРАВЛЕНО: {order_number}
         .route(
             "/api/orders/{order_number}/bank-info",
             get(get_bank_info_by_order),
         )
-        .with_state(pool);
+        // Отчеты
+        .route("/api/reports/weekly", get(get_weekly_report))
+        .layer(middleware::from_fn_with_state(pool.clone(), require_token))
+        .with_state(pool.clone());
+
+    reports::spawn_weekly_digest_task(pool);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
     println!("Server running on http://0.0.0.0:3000");
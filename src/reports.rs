@@ -0,0 +1,187 @@
+// --- ОТЧЕТЫ ---
+//
+// Периодический дайджест для складских менеджеров: топ материалов по стоимости,
+// суммарные траты за период и количество поставщиков на банк. Переиспользует те же
+// агрегаты, что и аналитические ручки в main.rs, плюс умеет сам себя рассылать по почте.
+
+use anyhow::Context;
+use chrono::{Duration, NaiveDate, Utc};
+use lettre::message::Message;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::env;
+
+use crate::BankSupplierCount;
+
+#[derive(Debug, Serialize)]
+pub struct TopMaterial {
+    pub material_name: String,
+    pub total_value: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Digest {
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub top_materials: Vec<TopMaterial>,
+    pub total_spent: Option<f64>,
+    pub suppliers_per_bank: Vec<BankSupplierCount>,
+}
+
+// Собирает дайджест за [period_start, period_end].
+pub async fn build_digest(
+    pool: &PgPool,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+) -> anyhow::Result<Digest> {
+    let top_materials = sqlx::query_as!(
+        TopMaterial,
+        r#"
+        SELECT
+            MC.material_name,
+            SUM(SU.quantity * SU.unit_price)::float AS total_value
+        FROM Storage_Units SU
+        JOIN Material_Catalog MC ON SU.material_id = MC.material_id
+        WHERE SU.date BETWEEN $1 AND $2
+        GROUP BY MC.material_name
+        ORDER BY total_value DESC
+        LIMIT 10
+        "#,
+        period_start,
+        period_end
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let total_spent = sqlx::query_scalar!(
+        "SELECT SUM(quantity * unit_price)::float FROM Storage_Units WHERE date BETWEEN $1 AND $2",
+        period_start,
+        period_end
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let suppliers_per_bank = sqlx::query_as!(
+        BankSupplierCount,
+        r#"
+        SELECT
+            bank_address_city,
+            COUNT(supplier_id) AS "supplier_count"
+        FROM Suppliers
+        WHERE bank_address_city IS NOT NULL
+        GROUP BY bank_address_city
+        ORDER BY "supplier_count" DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Digest {
+        period_start,
+        period_end,
+        top_materials,
+        total_spent,
+        suppliers_per_bank,
+    })
+}
+
+fn render_digest_text(digest: &Digest) -> String {
+    let mut body = format!(
+        "Отчет по складу за период {} — {}\n\nОбщие траты: {:.2}\n\nТоп материалов по стоимости:\n",
+        digest.period_start,
+        digest.period_end,
+        digest.total_spent.unwrap_or(0.0)
+    );
+    for material in &digest.top_materials {
+        body.push_str(&format!(
+            "  - {}: {:.2}\n",
+            material.material_name,
+            material.total_value.unwrap_or(0.0)
+        ));
+    }
+    body.push_str("\nПоставщиков на банк:\n");
+    for row in &digest.suppliers_per_bank {
+        body.push_str(&format!(
+            "  - {}: {}\n",
+            row.bank_address_city.as_deref().unwrap_or("(неизвестно)"),
+            row.supplier_count.unwrap_or(0)
+        ));
+    }
+    body
+}
+
+// Отправляет дайджест получателям из REPORT_RECIPIENTS (список адресов через запятую)
+// через SMTP. Если получатели не настроены, письмо просто не отправляется.
+// Транспорт асинхронный (Tokio1Executor), чтобы SMTP round-trip не блокировал воркер рантайма,
+// в отличие от синхронного SmtpTransport.
+async fn send_digest_email(digest: &Digest) -> anyhow::Result<()> {
+    let recipients = env::var("REPORT_RECIPIENTS").unwrap_or_default();
+    if recipients.trim().is_empty() {
+        return Ok(());
+    }
+
+    let smtp_host = env::var("SMTP_HOST").context("SMTP_HOST must be set to send reports")?;
+    let smtp_user = env::var("SMTP_USER").context("SMTP_USER must be set to send reports")?;
+    let smtp_password =
+        env::var("SMTP_PASSWORD").context("SMTP_PASSWORD must be set to send reports")?;
+    let sender = env::var("REPORT_SENDER").unwrap_or_else(|_| smtp_user.clone());
+
+    let body = render_digest_text(digest);
+    let credentials = Credentials::new(smtp_user, smtp_password);
+    let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp_host)
+        .context("не удалось создать SMTP-транспорт")?
+        .credentials(credentials)
+        .build();
+
+    for recipient in recipients.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let email = Message::builder()
+            .from(sender.parse().context("некорректный REPORT_SENDER")?)
+            .to(recipient.parse().context("некорректный получатель в REPORT_RECIPIENTS")?)
+            .subject(format!(
+                "Отчет по складу: {} — {}",
+                digest.period_start, digest.period_end
+            ))
+            .body(body.clone())
+            .context("не удалось собрать письмо")?;
+
+        mailer
+            .send(email)
+            .await
+            .context("не удалось отправить письмо")?;
+    }
+
+    Ok(())
+}
+
+// Запускает фоновую задачу, которая периодически (REPORT_INTERVAL_SECS, по умолчанию
+// раз в неделю) считает дайджест за прошедший период и рассылает его по почте.
+pub fn spawn_weekly_digest_task(pool: PgPool) {
+    let interval_secs: u64 = env::var("REPORT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(7 * 24 * 60 * 60);
+
+    tokio::spawn(async move {
+        let period = std::time::Duration::from_secs(interval_secs);
+        // interval() тикает сразу при первом вызове tick() — откладываем первый запуск
+        // на полный период, чтобы рестарт процесса не слал дайджест немедленно.
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            ticker.tick().await;
+
+            let period_end = Utc::now().date_naive();
+            let period_start = period_end - Duration::seconds(interval_secs as i64);
+
+            match build_digest(&pool, period_start, period_end).await {
+                Ok(digest) => {
+                    if let Err(err) = send_digest_email(&digest).await {
+                        eprintln!("Не удалось отправить дайджест по почте: {err:#}");
+                    }
+                }
+                Err(err) => eprintln!("Не удалось собрать дайджест для отчета: {err:#}"),
+            }
+        }
+    });
+}